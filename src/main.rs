@@ -1,17 +1,51 @@
+use imgui::Context as ImguiContext;
+use imgui_opengl_renderer::Renderer as ImguiRenderer;
+use imgui_sdl2::ImguiSdl2;
+use rand::Rng;
+use specs::{
+    Builder, Component, Dispatcher, DispatcherBuilder, Entities, Join, NullStorage,
+    Read, ReadStorage, System, VecStorage, World, WorldExt, Write, WriteStorage,
+};
 use sdl2::{
-    event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::WindowCanvas, video::Window,
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::Rect,
+    render::{TextureCreator, TextureQuery, WindowCanvas},
+    ttf::Sdl2TtfContext,
+    video::{FullscreenType, SwapInterval, Window, WindowContext},
+    VideoSubsystem,
+};
+use std::{
+    ops::Add,
+    time::{Duration, Instant},
 };
-use std::{ops::Add, time::Duration};
 
 const WINDOW_WEIGHT: u32 = 800;
 const WINDOW_HEIGHT: u32 = 600;
 const DOT_SIZE: u32 = 20;
 
+/// Simulation cadence. The snake advances one cell per tick regardless of how
+/// fast the machine renders.
+const TICK_DURATION: Duration = Duration::from_millis(150);
+
+/// Length the snake starts at; the score is its current length minus this.
+const START_LENGTH: usize = 3;
+
+/// TrueType font used for the HUD and banners.
+const FONT_PATH: &str = "assets/font.ttf";
+
+const GRID_WIDTH: i32 = (WINDOW_WEIGHT / DOT_SIZE) as i32;
+const GRID_HEIGHT: i32 = (WINDOW_HEIGHT / DOT_SIZE) as i32;
+
 pub enum State {
     Playing,
     Paused,
+    GameOver,
+    Won,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum MoveType {
     Up,
     Down,
@@ -19,69 +53,619 @@ pub enum MoveType {
     Left,
 }
 
-#[derive(Copy, Clone)]
+impl MoveType {
+    /// The 180° reversal of this heading. Feeding the snake its opposite would
+    /// walk the head straight into the neck, so input ignores it.
+    fn opposite(self) -> MoveType {
+        match self {
+            MoveType::Up => MoveType::Down,
+            MoveType::Down => MoveType::Up,
+            MoveType::Right => MoveType::Left,
+            MoveType::Left => MoveType::Right,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
 pub struct Point(pub i32, pub i32);
 
-pub struct GameContext {
-    pub position: Vec<Point>,
-    pub player_direction: MoveType,
-    pub food: Point,
-    pub state: State,
+/// A screen the main loop can run. States own their own input, simulation and
+/// drawing, and hand control to a sibling screen by returning it from
+/// `next_state`.
+pub trait AppState {
+    fn handle_event(&mut self, ev: &Event);
+    fn update(&mut self, dt: Duration);
+    fn draw(&self, r: &mut Renderer) -> Result<(), String>;
+    fn next_state(&mut self) -> Option<Box<dyn AppState>>;
+
+    /// Live values for the debug overlay; `None` for screens with nothing to
+    /// inspect.
+    fn debug_info(&self) -> Option<DebugInfo> {
+        None
+    }
+
+    /// Apply tuning from the debug overlay. States that ignore tuning keep the
+    /// default no-op.
+    fn tune(&mut self, _tick_ms: u64, _grid: i32) {}
+}
+
+/// Snapshot of gameplay state rendered by the debug overlay.
+pub struct DebugInfo {
+    pub snake_len: usize,
+    pub head: Point,
+    pub state: &'static str,
+    pub frames_per_tick: u32,
+}
+
+/// World-space grid cell. Duplicates `Point` so the ECS can store it directly.
+#[derive(Component, Copy, Clone)]
+#[storage(VecStorage)]
+pub struct Position(pub Point);
+
+/// The head's desired travel direction; only the head carries one.
+#[derive(Component, Copy, Clone)]
+#[storage(VecStorage)]
+pub struct Velocity(pub MoveType);
+
+/// Marks an entity as a body segment. `index` 0 is the head; the tail has the
+/// largest index.
+#[derive(Component, Copy, Clone)]
+#[storage(VecStorage)]
+pub struct SnakeSegment {
+    pub index: usize,
+}
+
+/// Marker for the single food entity.
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Food;
+
+/// Fill colour used by the render join.
+#[derive(Component, Copy, Clone)]
+#[storage(VecStorage)]
+pub struct Renderable {
+    pub color: Color,
+}
+
+/// Per-tick communication between the collision and growth systems.
+#[derive(Default)]
+pub struct TickFlags {
+    pub ate: bool,
+    pub dead: bool,
+    /// Set when the snake has filled the board and there is nowhere left to
+    /// spawn food — the player has won.
+    pub won: bool,
+    /// Cell the tail occupied *before* the last movement step. A new segment
+    /// grown on an eat tick is placed here so the snake re-occupies the vacated
+    /// cell instead of duplicating the post-move tail.
+    pub vacated_tail: Point,
+}
+
+/// Playfield bounds in cells, kept as a resource so the debug overlay can
+/// resize the grid at runtime.
+#[derive(Copy, Clone)]
+pub struct GridSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for GridSize {
+    fn default() -> GridSize {
+        GridSize {
+            width: GRID_WIDTH,
+            height: GRID_HEIGHT,
+        }
+    }
+}
+
+/// Advances the head by its `Velocity` and drags every other segment onto the
+/// cell its predecessor occupied.
+pub struct MovementSystem;
+
+impl<'a> System<'a> for MovementSystem {
+    type SystemData = (
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, SnakeSegment>,
+        ReadStorage<'a, Velocity>,
+        Write<'a, TickFlags>,
+    );
+
+    fn run(&mut self, (mut positions, segments, velocities, mut flags): Self::SystemData) {
+        let direction = (&velocities, &segments)
+            .join()
+            .find(|(_, seg)| seg.index == 0)
+            .map(|(vel, _)| vel.0);
+        let direction = match direction {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        let mut ordered: Vec<(usize, Point)> = (&segments, &positions)
+            .join()
+            .map(|(seg, pos)| (seg.index, pos.0))
+            .collect();
+        ordered.sort_by_key(|(index, _)| *index);
+
+        // Remember where the tail sits before it moves; the growth system grows
+        // the snake into this freed cell.
+        flags.vacated_tail = ordered[ordered.len() - 1].1;
+
+        let head = ordered[0].1;
+        let next_head = head
+            + match direction {
+                MoveType::Up => Point(0, -1),
+                MoveType::Down => Point(0, 1),
+                MoveType::Right => Point(1, 0),
+                MoveType::Left => Point(-1, 0),
+            };
+
+        let mut next: Vec<Point> = Vec::with_capacity(ordered.len());
+        next.push(next_head);
+        for window in 1..ordered.len() {
+            next.push(ordered[window - 1].1);
+        }
+
+        for (seg, pos) in (&segments, &mut positions).join() {
+            pos.0 = next[seg.index];
+        }
+    }
+}
+
+/// Detects the head leaving the grid, biting its own body, or reaching food,
+/// recording the result in `TickFlags`.
+pub struct CollisionSystem;
+
+impl<'a> System<'a> for CollisionSystem {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, SnakeSegment>,
+        ReadStorage<'a, Food>,
+        Write<'a, TickFlags>,
+        Read<'a, GridSize>,
+    );
+
+    fn run(&mut self, (positions, segments, foods, mut flags, grid): Self::SystemData) {
+        let head = (&positions, &segments)
+            .join()
+            .find(|(_, seg)| seg.index == 0)
+            .map(|(pos, _)| pos.0);
+        let head = match head {
+            Some(head) => head,
+            None => return,
+        };
+
+        let Point(x, y) = head;
+        if x < 0 || x >= grid.width || y < 0 || y >= grid.height {
+            flags.dead = true;
+        }
+
+        for (pos, seg) in (&positions, &segments).join() {
+            if seg.index != 0 && pos.0 == head {
+                flags.dead = true;
+            }
+        }
+
+        for (pos, _) in (&positions, &foods).join() {
+            if pos.0 == head {
+                flags.ate = true;
+            }
+        }
+    }
+}
+
+/// Pick a grid cell for food that no snake segment currently occupies.
+/// Returns `None` when the snake fills the board, so the caller can register a
+/// win instead of looping forever looking for a free cell.
+fn spawn_food<R: Rng>(occupied: &[Point], grid: GridSize, rng: &mut R) -> Option<Point> {
+    let free: Vec<Point> = (0..grid.width)
+        .flat_map(|x| (0..grid.height).map(move |y| Point(x, y)))
+        .filter(|cell| !occupied.contains(cell))
+        .collect();
+    if free.is_empty() {
+        return None;
+    }
+    Some(free[rng.gen_range(0..free.len())])
+}
+
+/// On eat, appends a segment at the cell the tail vacated this tick and moves
+/// the food to a free cell.
+pub struct GrowthSystem;
+
+impl<'a> System<'a> for GrowthSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, SnakeSegment>,
+        WriteStorage<'a, Renderable>,
+        ReadStorage<'a, Food>,
+        Write<'a, TickFlags>,
+        Read<'a, GridSize>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut positions, mut segments, mut renderables, foods, mut flags, grid): Self::SystemData,
+    ) {
+        if !flags.ate {
+            return;
+        }
+        flags.ate = false;
+
+        let tail_index = (&segments)
+            .join()
+            .map(|seg| seg.index)
+            .max();
+        let tail_index = match tail_index {
+            Some(index) => index,
+            None => return,
+        };
+
+        // Grow into the cell the tail vacated this tick, not its post-move
+        // cell, so the body stays contiguous without a duplicated segment.
+        let segment = entities.create();
+        positions
+            .insert(segment, Position(flags.vacated_tail))
+            .ok();
+        segments
+            .insert(segment, SnakeSegment { index: tail_index + 1 })
+            .ok();
+        renderables
+            .insert(segment, Renderable { color: Color::GREEN })
+            .ok();
+
+        let occupied: Vec<Point> = (&positions, &segments).join().map(|(p, _)| p.0).collect();
+        let mut rng = rand::thread_rng();
+        match spawn_food(&occupied, *grid, &mut rng) {
+            Some(food_cell) => {
+                for (pos, _) in (&mut positions, &foods).join() {
+                    pos.0 = food_cell;
+                }
+            }
+            None => flags.won = true,
+        }
+    }
 }
 
 pub struct Renderer {
     canvas: WindowCanvas,
+    texture_creator: TextureCreator<WindowContext>,
+    ttf_context: Sdl2TtfContext,
+    video: VideoSubsystem,
 }
 
-impl GameContext {
-    pub fn new() -> GameContext {
-        GameContext {
-            position: vec![Point(3, 1), Point(2, 1), Point(1, 1)],
-            player_direction: MoveType::Right,
-            food: Point(3, 3),
+/// Gameplay scene, backed by a `specs` world and a system dispatcher.
+pub struct Playing {
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    state: State,
+    /// Direction last applied on a tick. Input is validated against this
+    /// latched heading rather than the pending `Velocity`, so several turns
+    /// within one tick can't chain into a 180° reversal.
+    heading: MoveType,
+    accumulator: Duration,
+    tick_duration: Duration,
+    frames_per_tick: u32,
+    frames_since_tick: u32,
+}
+
+impl Playing {
+    pub fn new() -> Playing {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<SnakeSegment>();
+        world.register::<Food>();
+        world.register::<Renderable>();
+        world.insert(TickFlags::default());
+        world.insert(GridSize::default());
+
+        let head_cells = [Point(3, 1), Point(2, 1), Point(1, 1)];
+        for (index, cell) in head_cells.iter().enumerate() {
+            let mut builder = world
+                .create_entity()
+                .with(Position(*cell))
+                .with(SnakeSegment { index })
+                .with(Renderable { color: Color::GREEN });
+            if index == 0 {
+                builder = builder.with(Velocity(MoveType::Right));
+            }
+            builder.build();
+        }
+
+        world
+            .create_entity()
+            .with(Position(Point(3, 3)))
+            .with(Food)
+            .with(Renderable { color: Color::RED })
+            .build();
+
+        let dispatcher = DispatcherBuilder::new()
+            .with(MovementSystem, "movement", &[])
+            .with(CollisionSystem, "collision", &["movement"])
+            .with(GrowthSystem, "growth", &["collision"])
+            .build();
+
+        Playing {
+            world,
+            dispatcher,
             state: State::Paused,
+            heading: MoveType::Right,
+            accumulator: Duration::ZERO,
+            tick_duration: TICK_DURATION,
+            frames_per_tick: 0,
+            frames_since_tick: 0,
         }
     }
 
-    pub fn next_tick(&mut self) {
-        if let State::Paused = self.state {
+    fn next_tick(&mut self) {
+        match self.state {
+            State::Paused | State::GameOver | State::Won => return,
+            State::Playing => {}
+        }
+
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+        self.frames_per_tick = self.frames_since_tick;
+        self.frames_since_tick = 0;
+
+        // Latch the direction that was actually applied this tick; the next
+        // batch of input is validated against it.
+        let heading = {
+            let segments = self.world.read_storage::<SnakeSegment>();
+            let velocities = self.world.read_storage::<Velocity>();
+            (&velocities, &segments)
+                .join()
+                .find(|(_, seg)| seg.index == 0)
+                .map(|(vel, _)| vel.0)
+        };
+        if let Some(heading) = heading {
+            self.heading = heading;
+        }
+
+        let flags = self.world.read_resource::<TickFlags>();
+        if flags.dead {
+            self.state = State::GameOver;
+        } else if flags.won {
+            self.state = State::Won;
+        }
+    }
+
+    fn set_direction(&mut self, direction: MoveType) {
+        if direction == self.heading.opposite() {
             return;
         }
+        let segments = self.world.read_storage::<SnakeSegment>();
+        let mut velocities = self.world.write_storage::<Velocity>();
+        for (vel, seg) in (&mut velocities, &segments).join() {
+            if seg.index == 0 {
+                vel.0 = direction;
+            }
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            State::Playing => State::Paused,
+            State::Paused => State::Playing,
+            State::GameOver => State::GameOver,
+            State::Won => State::Won,
+        }
+    }
+
+    fn score(&self) -> usize {
+        self.world
+            .read_storage::<SnakeSegment>()
+            .join()
+            .count()
+            .saturating_sub(START_LENGTH)
+    }
+}
+
+impl Default for Playing {
+    fn default() -> Playing {
+        Playing::new()
+    }
+}
+
+impl AppState for Playing {
+    fn handle_event(&mut self, ev: &Event) {
+        if let Event::KeyDown {
+            keycode: Some(keycode),
+            ..
+        } = ev
+        {
+            match keycode {
+                Keycode::W => self.set_direction(MoveType::Up),
+                Keycode::A => self.set_direction(MoveType::Left),
+                Keycode::S => self.set_direction(MoveType::Down),
+                Keycode::D => self.set_direction(MoveType::Right),
+                Keycode::Escape => self.toggle_pause(),
+                _ => {}
+            }
+        }
+    }
+
+    fn update(&mut self, dt: Duration) {
+        self.frames_since_tick += 1;
+        self.accumulator += dt;
+        while self.accumulator >= self.tick_duration {
+            self.next_tick();
+            self.accumulator -= self.tick_duration;
+        }
+    }
+
+    fn debug_info(&self) -> Option<DebugInfo> {
+        let positions = self.world.read_storage::<Position>();
+        let segments = self.world.read_storage::<SnakeSegment>();
+        let head = (&positions, &segments)
+            .join()
+            .find(|(_, seg)| seg.index == 0)
+            .map(|(pos, _)| pos.0)
+            .unwrap_or(Point(0, 0));
 
-        let head_position = self.position.first().unwrap();
-        let next_head_position = match self.player_direction {
-            MoveType::Up => *head_position + Point(0, -1),
-            MoveType::Down => *head_position + Point(0, 1),
-            MoveType::Right => *head_position + Point(1, 0),
-            MoveType::Left => *head_position + Point(-1, 0),
+        Some(DebugInfo {
+            snake_len: segments.join().count(),
+            head,
+            state: match self.state {
+                State::Playing => "Playing",
+                State::Paused => "Paused",
+                State::GameOver => "GameOver",
+                State::Won => "Won",
+            },
+            frames_per_tick: self.frames_per_tick,
+        })
+    }
+
+    fn tune(&mut self, tick_ms: u64, grid: i32) {
+        self.tick_duration = Duration::from_millis(tick_ms);
+        // The overlay exposes a single grid slider; derive the height from the
+        // window aspect ratio so the cells stay square and the playfield never
+        // extends past the visible window. At the default width this reproduces
+        // the original 40x30 grid exactly.
+        let mut grid_size = self.world.write_resource::<GridSize>();
+        grid_size.width = grid;
+        grid_size.height = grid * WINDOW_HEIGHT as i32 / WINDOW_WEIGHT as i32;
+    }
+
+    fn draw(&self, r: &mut Renderer) -> Result<(), String> {
+        let background = match self.state {
+            State::Playing => Color::RGB(0, 0, 0),
+            State::Paused => Color::RGB(30, 30, 30),
+            State::GameOver => Color::RGB(60, 0, 0),
+            State::Won => Color::RGB(0, 60, 0),
         };
+        r.clear(background);
+
+        let positions = self.world.read_storage::<Position>();
+        let renderables = self.world.read_storage::<Renderable>();
+        for (pos, renderable) in (&positions, &renderables).join() {
+            r.fill_dot(renderable.color, &pos.0)?;
+        }
 
-        self.position.pop();
-        self.position.reverse();
-        self.position.push(next_head_position);
-        self.position.reverse();
+        r.render_text(
+            &format!("Score: {}", self.score()),
+            18,
+            Point(8, 4),
+            Color::WHITE,
+        )?;
+        if let State::Paused = self.state {
+            r.render_text("PAUSED", 48, Point(300, 260), Color::WHITE)?;
+        }
+        if let State::Won = self.state {
+            r.render_text("YOU WIN", 48, Point(280, 260), Color::WHITE)?;
+        }
+
+        Ok(())
     }
 
-    pub fn move_up(&mut self) {
-        self.player_direction = MoveType::Up;
+    fn next_state(&mut self) -> Option<Box<dyn AppState>> {
+        match self.state {
+            State::GameOver | State::Won => Some(Box::new(GameOverScreen::new())),
+            _ => None,
+        }
     }
+}
 
-    pub fn move_down(&mut self) {
-        self.player_direction = MoveType::Down;
+/// The first screen the player sees. Advances to gameplay on <Return>.
+pub struct TitleScreen {
+    start: bool,
+}
+
+impl TitleScreen {
+    pub fn new() -> TitleScreen {
+        TitleScreen { start: false }
     }
+}
 
-    pub fn move_right(&mut self) {
-        self.player_direction = MoveType::Right;
+impl Default for TitleScreen {
+    fn default() -> TitleScreen {
+        TitleScreen::new()
     }
+}
 
-    pub fn move_left(&mut self) {
-        self.player_direction = MoveType::Left;
+impl AppState for TitleScreen {
+    fn handle_event(&mut self, ev: &Event) {
+        if let Event::KeyDown {
+            keycode: Some(Keycode::Return),
+            ..
+        } = ev
+        {
+            self.start = true;
+        }
     }
 
-    pub fn toggle_pause(&mut self) {
-        self.state = match self.state {
-            State::Playing => State::Paused,
-            State::Paused => State::Playing,
+    fn update(&mut self, _dt: Duration) {}
+
+    fn draw(&self, r: &mut Renderer) -> Result<(), String> {
+        r.clear(Color::RGB(0, 40, 0));
+        r.render_text("SNAKE", 64, Point(300, 180), Color::WHITE)?;
+        r.render_text(
+            "press Return to play",
+            28,
+            Point(230, 300),
+            Color::WHITE,
+        )?;
+
+        Ok(())
+    }
+
+    fn next_state(&mut self) -> Option<Box<dyn AppState>> {
+        if self.start {
+            Some(Box::new(Playing::new()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Shown after a collision or after the snake fills the board. Restarts a
+/// fresh game on <R>.
+pub struct GameOverScreen {
+    restart: bool,
+}
+
+impl GameOverScreen {
+    pub fn new() -> GameOverScreen {
+        GameOverScreen { restart: false }
+    }
+}
+
+impl Default for GameOverScreen {
+    fn default() -> GameOverScreen {
+        GameOverScreen::new()
+    }
+}
+
+impl AppState for GameOverScreen {
+    fn handle_event(&mut self, ev: &Event) {
+        if let Event::KeyDown {
+            keycode: Some(Keycode::R),
+            ..
+        } = ev
+        {
+            self.restart = true;
+        }
+    }
+
+    fn update(&mut self, _dt: Duration) {}
+
+    fn draw(&self, r: &mut Renderer) -> Result<(), String> {
+        r.clear(Color::RGB(60, 0, 0));
+        r.render_text(
+            "GAME OVER - press R to restart",
+            32,
+            Point(120, 260),
+            Color::WHITE,
+        )?;
+
+        Ok(())
+    }
+
+    fn next_state(&mut self) -> Option<Box<dyn AppState>> {
+        if self.restart {
+            Some(Box::new(Playing::new()))
+        } else {
+            None
         }
     }
 }
@@ -95,12 +679,101 @@ impl Add<Point> for Point {
 }
 
 impl Renderer {
-    pub fn new(window: Window) -> Result<Renderer, String> {
+    pub fn new(
+        window: Window,
+        ttf_context: Sdl2TtfContext,
+        video: VideoSubsystem,
+    ) -> Result<Renderer, String> {
+        // Force the SDL renderer onto the OpenGL backend so the canvas shares
+        // the GL context the imgui overlay draws into; otherwise SDL might pick
+        // a different driver and the overlay renders into a context that is
+        // never presented.
+        sdl2::hint::set("SDL_RENDER_DRIVER", "opengl");
         let canvas = window
             .into_canvas()
+            .accelerated()
             .build()
             .map_err(|err| err.to_string())?;
-        Ok(Renderer { canvas })
+        let texture_creator = canvas.texture_creator();
+        Ok(Renderer {
+            canvas,
+            texture_creator,
+            ttf_context,
+            video,
+        })
+    }
+
+    /// Cycle the window between windowed and borderless desktop fullscreen.
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let next = match self.canvas.window().fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        self.canvas.window_mut().set_fullscreen(next)
+    }
+
+    /// Sync presentation to the monitor's refresh (`on`) or let it run
+    /// unthrottled. Swallows the error on drivers that refuse the mode.
+    pub fn set_vsync(&mut self, on: bool) {
+        let interval = if on {
+            SwapInterval::VSync
+        } else {
+            SwapInterval::Immediate
+        };
+        self.video.gl_set_swap_interval(interval).ok();
+    }
+
+    /// Draw `text` at `pos`, rendered from the bundled TrueType font. The glyph
+    /// texture is created fresh each call, which is cheap for the short HUD
+    /// strings we draw.
+    ///
+    /// If the font asset can't be loaded the text is silently skipped rather
+    /// than failing the whole frame — a missing HUD is preferable to the window
+    /// closing the instant gameplay starts.
+    pub fn render_text(
+        &mut self,
+        text: &str,
+        size: u16,
+        pos: Point,
+        color: Color,
+    ) -> Result<(), String> {
+        let font = match self.ttf_context.load_font(FONT_PATH, size) {
+            Ok(font) => font,
+            Err(_) => return Ok(()),
+        };
+        let surface = font
+            .render(text)
+            .blended(color)
+            .map_err(|err| err.to_string())?;
+        let texture = self
+            .texture_creator
+            .create_texture_from_surface(&surface)
+            .map_err(|err| err.to_string())?;
+
+        let TextureQuery { width, height, .. } = texture.query();
+        let Point(x, y) = pos;
+        self.canvas
+            .copy(&texture, None, Some(Rect::new(x, y, width, height)))?;
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.canvas.set_draw_color(color);
+        self.canvas.clear();
+    }
+
+    pub fn fill_dot(&mut self, color: Color, point: &Point) -> Result<(), String> {
+        self.canvas.set_draw_color(color);
+        self.draw_dot(point)
+    }
+
+    pub fn present(&mut self) {
+        self.canvas.present();
+    }
+
+    pub fn window(&self) -> &Window {
+        self.canvas.window()
     }
 
     fn draw_dot(&mut self, point: &Point) -> Result<(), String> {
@@ -114,92 +787,364 @@ impl Renderer {
 
         Ok(())
     }
+}
 
-    pub fn draw(&mut self, context: &GameContext) -> Result<(), String> {
-        self.draw_background(context);
-        self.draw_player(context)?;
-        self.draw_food(context)?;
-        self.canvas.present();
+/// Live debug panel drawn on top of the canvas with imgui. Shows gameplay
+/// values and exposes sliders for tick speed and grid size.
+pub struct DebugOverlay {
+    imgui: ImguiContext,
+    platform: ImguiSdl2,
+    renderer: ImguiRenderer,
+    visible: bool,
+    tick_ms: i32,
+    grid: i32,
+}
 
-        Ok(())
+impl DebugOverlay {
+    pub fn new(window: &Window, video: &sdl2::VideoSubsystem) -> DebugOverlay {
+        let mut imgui = ImguiContext::create();
+        imgui.set_ini_filename(None);
+        let platform = ImguiSdl2::new(&mut imgui, window);
+        let renderer = ImguiRenderer::new(&mut imgui, |name| {
+            video.gl_get_proc_address(name) as _
+        });
+
+        DebugOverlay {
+            imgui,
+            platform,
+            renderer,
+            visible: true,
+            tick_ms: TICK_DURATION.as_millis() as i32,
+            grid: GRID_WIDTH,
+        }
     }
 
-    fn draw_background(&mut self, context: &GameContext) {
-        let color = match context.state {
-            State::Playing => Color::RGB(0, 0, 0),
-            State::Paused => Color::RGB(30, 30, 30),
-        };
-        self.canvas.set_draw_color(color);
-        self.canvas.clear();
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        self.platform.handle_event(&mut self.imgui, event);
     }
 
-    fn draw_player(&mut self, context: &GameContext) -> Result<(), String> {
-        self.canvas.set_draw_color(Color::GREEN);
-        for point in &context.position {
-            self.draw_dot(point)?;
+    /// Draw the panel into the back buffer. Returns the tuning values (tick
+    /// milliseconds, grid width in cells) only when a slider actually moved, so
+    /// merely enabling the overlay never perturbs the running simulation.
+    pub fn render(
+        &mut self,
+        window: &Window,
+        mouse_state: &sdl2::mouse::MouseState,
+        info: &DebugInfo,
+    ) -> Option<(u64, i32)> {
+        self.platform
+            .prepare_frame(self.imgui.io_mut(), window, mouse_state);
+
+        let mut tick_ms = self.tick_ms;
+        let mut grid = self.grid;
+        let visible = self.visible;
+        let ui = self.imgui.frame();
+        if visible {
+            imgui::Window::new(imgui::im_str!("debug")).build(&ui, || {
+                ui.text(format!("length: {}", info.snake_len));
+                ui.text(format!("head: ({}, {})", info.head.0, info.head.1));
+                ui.text(format!("state: {}", info.state));
+                ui.text(format!("frames/tick: {}", info.frames_per_tick));
+                imgui::Slider::new(imgui::im_str!("tick ms"))
+                    .range(30..=500)
+                    .build(&ui, &mut tick_ms);
+                imgui::Slider::new(imgui::im_str!("grid"))
+                    .range(10..=GRID_WIDTH)
+                    .build(&ui, &mut grid);
+            });
         }
+        self.platform.prepare_render(&ui, window);
+        self.renderer.render(ui);
 
-        Ok(())
+        let changed = tick_ms != self.tick_ms || grid != self.grid;
+        self.tick_ms = tick_ms;
+        self.grid = grid;
+        if changed {
+            Some((tick_ms as u64, grid))
+        } else {
+            None
+        }
     }
+}
 
-    fn draw_food(&mut self, context: &GameContext) -> Result<(), String> {
-        self.canvas.set_draw_color(Color::RED);
-        self.draw_dot(&context.food)?;
+/// Builds the SDL window/renderer and drives a `Box<dyn AppState>`, swapping in
+/// whatever a state returns from `next_state`.
+pub struct AppBuilder {
+    title: String,
+    width: u32,
+    height: u32,
+    state: Option<Box<dyn AppState>>,
+    debug: bool,
+}
 
-        Ok(())
+impl Default for AppBuilder {
+    fn default() -> AppBuilder {
+        AppBuilder::new()
     }
 }
 
-fn game_loop() -> Result<(), String> {
-    let sdl_context = sdl2::init()?;
-    let video_subsystem = sdl_context.video()?;
-
-    let window = video_subsystem
-        .window("Snake_game", WINDOW_WEIGHT, WINDOW_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|err| err.to_string())?;
-
-    let mut context = GameContext::new();
-    let mut renderer = Renderer::new(window)?;
-
-    let mut event_pump = sdl_context.event_pump()?;
-    let mut frame_counter = 0;
-
-    'running: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => break 'running,
-                Event::KeyDown {
-                    keycode: Some(keycode),
-                    ..
-                } => match keycode {
-                    Keycode::W => context.move_up(),
-                    Keycode::A => context.move_left(),
-                    Keycode::S => context.move_down(),
-                    Keycode::D => context.move_right(),
-                    Keycode::Escape => context.toggle_pause(),
-                    _ => {}
-                },
-                _ => {}
-            }
+impl AppBuilder {
+    pub fn new() -> AppBuilder {
+        AppBuilder {
+            title: "Snake_game".to_string(),
+            width: WINDOW_WEIGHT,
+            height: WINDOW_HEIGHT,
+            state: None,
+            debug: false,
         }
+    }
 
-        std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+    pub fn with_debug(mut self, debug: bool) -> AppBuilder {
+        self.debug = debug;
+        self
+    }
 
-        frame_counter += 1;
-        if frame_counter % 10 == 0 {
-            context.next_tick();
-            frame_counter = 0;
-        }
+    pub fn with_title(mut self, title: &str) -> AppBuilder {
+        self.title = title.to_string();
+        self
+    }
 
-        renderer.draw(&context)?;
+    pub fn with_resolution(mut self, width: u32, height: u32) -> AppBuilder {
+        self.width = width;
+        self.height = height;
+        self
     }
 
-    Ok(())
+    pub fn with_state(mut self, state: Box<dyn AppState>) -> AppBuilder {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn run(self) -> Result<(), String> {
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+
+        let window = video_subsystem
+            .window(&self.title, self.width, self.height)
+            .position_centered()
+            .opengl()
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        let ttf_context = sdl2::ttf::init().map_err(|err| err.to_string())?;
+        let mut renderer = Renderer::new(window, ttf_context, video_subsystem.clone())?;
+        renderer.set_vsync(true);
+        let mut event_pump = sdl_context.event_pump()?;
+        let mut state: Box<dyn AppState> =
+            self.state.unwrap_or_else(|| Box::new(TitleScreen::new()));
+        let mut overlay = if self.debug {
+            Some(DebugOverlay::new(renderer.window(), &video_subsystem))
+        } else {
+            None
+        };
+
+        let mut previous = Instant::now();
+
+        'running: loop {
+            for event in event_pump.poll_iter() {
+                if let Some(overlay) = overlay.as_mut() {
+                    overlay.handle_event(&event);
+                }
+                match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F1),
+                        ..
+                    } => {
+                        if let Some(overlay) = overlay.as_mut() {
+                            overlay.toggle();
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F11),
+                        ..
+                    } => renderer.toggle_fullscreen()?,
+                    ev => state.handle_event(&ev),
+                }
+            }
+
+            let now = Instant::now();
+            let elapsed = now - previous;
+            previous = now;
+
+            // Advance the simulation by however much real time has passed; the
+            // state drains its own accumulator in fixed steps. Render exactly
+            // once per outer iteration, decoupled from the tick rate.
+            state.update(elapsed);
+            state.draw(&mut renderer)?;
+
+            // Draw the debug panel into the back buffer before the swap, and
+            // feed any slider changes back into the active state.
+            if let Some(overlay) = overlay.as_mut() {
+                if let Some(info) = state.debug_info() {
+                    let mouse_state = event_pump.mouse_state();
+                    if let Some((tick_ms, grid)) =
+                        overlay.render(renderer.window(), &mouse_state, &info)
+                    {
+                        state.tune(tick_ms, grid);
+                    }
+                }
+            }
+
+            // Present once per iteration, after both the canvas and the overlay
+            // have drawn into the back buffer.
+            renderer.present();
+
+            if let Some(next) = state.next_state() {
+                state = next;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 fn main() {
-    game_loop().ok();
+    let debug = std::env::args().any(|arg| arg == "--debug");
+    AppBuilder::new()
+        .with_title("Snake_game")
+        .with_resolution(WINDOW_WEIGHT, WINDOW_HEIGHT)
+        .with_debug(debug)
+        .with_state(Box::new(TitleScreen::new()))
+        .run()
+        .ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::RunNow;
+
+    /// A small world with every gameplay component registered and the tick
+    /// resources in place, on a 5x5 grid.
+    fn test_world() -> World {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<SnakeSegment>();
+        world.register::<Food>();
+        world.register::<Renderable>();
+        world.insert(TickFlags::default());
+        world.insert(GridSize {
+            width: 5,
+            height: 5,
+        });
+        world
+    }
+
+    #[test]
+    fn spawn_food_rejects_occupied_cells() {
+        // A 2x1 grid with one cell taken leaves exactly one free cell, so the
+        // spawner must reject the occupied candidate and return the other.
+        let grid = GridSize {
+            width: 2,
+            height: 1,
+        };
+        let occupied = [Point(0, 0)];
+        let mut rng = rand::thread_rng();
+        for _ in 0..64 {
+            assert_eq!(spawn_food(&occupied, grid, &mut rng), Some(Point(1, 0)));
+        }
+    }
+
+    #[test]
+    fn spawn_food_returns_none_when_board_full() {
+        // Every cell of a 2x1 grid is occupied, so there is nowhere to spawn.
+        let grid = GridSize {
+            width: 2,
+            height: 1,
+        };
+        let occupied = [Point(0, 0), Point(1, 0)];
+        let mut rng = rand::thread_rng();
+        assert_eq!(spawn_food(&occupied, grid, &mut rng), None);
+    }
+
+    #[test]
+    fn collision_flags_head_leaving_grid() {
+        let mut world = test_world();
+        world
+            .create_entity()
+            .with(Position(Point(5, 0)))
+            .with(SnakeSegment { index: 0 })
+            .build();
+
+        CollisionSystem.run_now(&world);
+        world.maintain();
+
+        assert!(world.read_resource::<TickFlags>().dead);
+    }
+
+    #[test]
+    fn collision_flags_self_bite() {
+        let mut world = test_world();
+        world
+            .create_entity()
+            .with(Position(Point(1, 1)))
+            .with(SnakeSegment { index: 0 })
+            .build();
+        world
+            .create_entity()
+            .with(Position(Point(1, 1)))
+            .with(SnakeSegment { index: 1 })
+            .build();
+
+        CollisionSystem.run_now(&world);
+        world.maintain();
+
+        assert!(world.read_resource::<TickFlags>().dead);
+    }
+
+    #[test]
+    fn growth_appends_segment_at_vacated_cell() {
+        let mut world = test_world();
+        world
+            .create_entity()
+            .with(Position(Point(2, 2)))
+            .with(SnakeSegment { index: 0 })
+            .with(Renderable { color: Color::GREEN })
+            .build();
+        world
+            .create_entity()
+            .with(Position(Point(1, 2)))
+            .with(SnakeSegment { index: 1 })
+            .with(Renderable { color: Color::GREEN })
+            .build();
+        world
+            .create_entity()
+            .with(Position(Point(0, 0)))
+            .with(Food)
+            .with(Renderable { color: Color::RED })
+            .build();
+        {
+            let mut flags = world.write_resource::<TickFlags>();
+            flags.ate = true;
+            flags.vacated_tail = Point(0, 2);
+        }
+
+        GrowthSystem.run_now(&world);
+        world.maintain();
+
+        let positions = world.read_storage::<Position>();
+        let segments = world.read_storage::<SnakeSegment>();
+        let foods = world.read_storage::<Food>();
+
+        // A third segment now occupies the cell the tail vacated.
+        assert_eq!((&segments).join().count(), 3);
+        assert!((&positions, &segments)
+            .join()
+            .any(|(pos, _)| pos.0 == Point(0, 2)));
+
+        // Food respawned onto a cell no segment occupies.
+        let occupied: Vec<Point> = (&positions, &segments).join().map(|(p, _)| p.0).collect();
+        for (pos, _) in (&positions, &foods).join() {
+            assert!(!occupied.contains(&pos.0));
+        }
+
+        drop((positions, segments, foods));
+        assert!(!world.read_resource::<TickFlags>().ate);
+    }
 }